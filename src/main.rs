@@ -3,41 +3,64 @@ use std::error::Error;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{env, fmt, fs, process};
 
 use anyhow::Result as AnyResult;
 use clap::{Parser, ValueEnum};
+use once_cell::sync::OnceCell;
+use serde_json::Value;
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 const PROFDATA_FILE: &str = "rust_coverage.profdata";
 const IGNORE_REGEXES: &[&str] = &["\\.cargo/registry", "library/std"];
+// llvm-cov's reported percentages can jitter by a tiny amount between runs of the same data,
+// so thresholds are compared with this much slack to avoid flaking right at the boundary.
+const PERCENT_EPSILON: f64 = 0.0001;
 
 #[derive(Copy, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum OutputFormat {
     Html,
     Report,
     Lcov,
+    Json,
 }
 
 /// Convert "profraw" coverage data to:
 /// * HTML reports
 /// * terminal table reports
 /// * LCOV files (for upload to codecov etc.)
+/// * llvm-cov's native JSON format (for custom dashboards)
+///
+/// BREAKING CHANGE: `output-format` used to be a required positional argument (`coverage-prepare
+/// lcov bin1 bin2`), it's now the repeatable `--output-format` flag (`coverage-prepare
+/// --output-format lcov bin1 bin2`), so more than one format can be generated from a single merge
+/// pass. Update existing invocations accordingly.
 ///
 /// See <https://github.com/samuelcolvin/coverage-prepare/> for more information.
 #[derive(Parser, Debug)]
 #[clap(author, version, verbatim_doc_comment)]
 struct Cli {
-    /// output format
-    #[clap(arg_enum, value_parser)]
-    output_format: OutputFormat,
+    /// output format, repeat to generate multiple formats from a single merge pass,
+    /// e.g. `--output-format lcov --output-format html`
+    #[clap(arg_enum, long, value_parser, required = true)]
+    output_format: Vec<OutputFormat>,
 
-    /// binary files to build coverage from
+    /// binary files to build coverage from, if omitted `--discover-binaries` must be set
     #[clap(value_parser)]
     binaries: Vec<String>,
 
+    /// directory to recursively search for instrumented binaries, used when `binaries` is empty;
+    /// binaries are identified by inspecting their magic bytes (ELF, Mach-O, PE/EXE & DLL) rather than extension
+    #[clap(long, value_parser)]
+    discover_binaries: Option<String>,
+
+    /// directory to recursively search for `.profraw` files, repeat to search multiple directories,
+    /// defaults to the current directory
+    #[clap(long, value_parser)]
+    profraw_dir: Vec<String>,
+
     /// Output path, defaults to `rust_coverage.lcov` for lcov output, and `htmlcov/rust` for html output
     #[clap(short, long, value_parser)]
     output_path: Option<String>,
@@ -51,6 +74,26 @@ struct Cli {
     /// after generating the coverage reports, by default these files are deleted
     #[clap(long, value_parser)]
     no_delete: bool,
+
+    /// fail (exit code 1) if line coverage is below this percentage
+    #[clap(long, value_parser)]
+    fail_under_lines: Option<f64>,
+
+    /// fail (exit code 1) if function coverage is below this percentage
+    #[clap(long, value_parser)]
+    fail_under_functions: Option<f64>,
+
+    /// fail (exit code 1) if region coverage is below this percentage
+    #[clap(long, value_parser)]
+    fail_under_regions: Option<f64>,
+
+    /// path to the `llvm-profdata` binary, overriding auto-detection
+    #[clap(long, value_parser)]
+    llvm_profdata_path: Option<String>,
+
+    /// path to the `llvm-cov` binary, overriding auto-detection
+    #[clap(long, value_parser)]
+    llvm_cov_path: Option<String>,
 }
 
 macro_rules! cprint {
@@ -68,10 +111,28 @@ macro_rules! cprint {
 
 fn main() {
     let mut out = StandardStream::stderr(ColorChoice::Auto);
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
     if cli.binaries.is_empty() {
-        cprint!(out, Red, "No binary files specified");
-        process::exit(1);
+        match &cli.discover_binaries {
+            Some(dir) => match discover_binaries(Path::new(dir)) {
+                Ok(binaries) if binaries.is_empty() => {
+                    cprint!(out, Red, "No instrumented binaries found in {}", dir);
+                    process::exit(1);
+                }
+                Ok(binaries) => {
+                    cprint!(out, Green, "Discovered {} binaries in {}", binaries.len(), dir);
+                    cli.binaries = binaries;
+                }
+                Err(err) => {
+                    cprint!(out, Red, "{}", err);
+                    process::exit(1);
+                }
+            },
+            None => {
+                cprint!(out, Red, "No binary files specified");
+                process::exit(1);
+            }
+        }
     }
 
     match run(&mut out, cli) {
@@ -84,23 +145,48 @@ fn main() {
 }
 
 fn run(out: &mut StandardStream, cli: Cli) -> AnyResult<()> {
-    let profraw_files = merge_raw(out)?;
+    if cli.output_format.len() > 1 && cli.output_path.is_some() {
+        return Err(StringError::new("`--output-path` can only be used with a single `--output-format`").into());
+    }
+    let profraw_dirs = if cli.profraw_dir.is_empty() {
+        vec!["./".to_string()]
+    } else {
+        cli.profraw_dir.clone()
+    };
+    let profraw_files = merge_raw(out, &profraw_dirs, cli.llvm_profdata_path.as_deref())?;
     let no_delete = cli.no_delete;
-    cov(out, cli)?;
+    let demangler = if rustfilt_available() {
+        Some("-Xdemangler=rustfilt")
+    } else {
+        cprint!(
+            out,
+            Yellow,
+            "`rustfilt` not found on $PATH, reports will show mangled Rust symbol names"
+        );
+        None
+    };
+    for format in cli.output_format.clone() {
+        cov(out, &cli, format, demangler)?;
+    }
+    check_fail_under(&cli, demangler)?;
     maybe_delete(out, no_delete, profraw_files)
 }
 
-fn merge_raw(out: &mut StandardStream) -> AnyResult<Vec<String>> {
+fn merge_raw(
+    out: &mut StandardStream,
+    profraw_dirs: &[String],
+    llvm_profdata_path: Option<&str>,
+) -> AnyResult<Vec<String>> {
     let mut profraw_files = vec![];
-
-    for dir_entry in fs::read_dir("./")? {
-        let path = dir_entry?.path();
-        if path.is_file() && path.extension() == Some(OsStr::new("profraw")) {
-            profraw_files.push(path.to_string_lossy().to_string());
-        }
+    for dir in profraw_dirs {
+        walk_files(Path::new(dir), &has_extension("profraw"), &mut profraw_files)?;
     }
     if profraw_files.is_empty() {
-        return Err(StringError::new("No .profraw files found in CWD").into());
+        return Err(StringError::new(format!(
+            "No .profraw files found in {}",
+            profraw_dirs.join(", ")
+        ))
+        .into());
     }
 
     let mut args = vec!["merge", "-sparse"];
@@ -119,18 +205,19 @@ fn merge_raw(out: &mut StandardStream) -> AnyResult<Vec<String>> {
     } else {
         cprint!(out, Green, "Merging {} .profraw files into {}", count, PROFDATA_FILE);
     }
-    execute("profdata", &args, false)?;
+    execute("profdata", llvm_profdata_path, &args, false)?;
     Ok(profraw_files)
 }
 
-fn cov(out: &mut StandardStream, cli: Cli) -> AnyResult<()> {
-    let command = match cli.output_format {
+fn cov(out: &mut StandardStream, cli: &Cli, output_format: OutputFormat, demangler: Option<&str>) -> AnyResult<()> {
+    let command = match output_format {
         OutputFormat::Html => "show",
         OutputFormat::Report => "report",
-        OutputFormat::Lcov => "export",
+        OutputFormat::Lcov | OutputFormat::Json => "export",
     };
     let profile = format!("-instr-profile={}", PROFDATA_FILE);
-    let mut args = vec![command, "-Xdemangler=rustfilt", &profile];
+    let mut args = vec![command, &profile];
+    args.extend(demangler);
     let mut ignore_regexes = IGNORE_REGEXES
         .iter()
         .map(|r| format!("--ignore-filename-regex={}", r))
@@ -144,9 +231,9 @@ fn cov(out: &mut StandardStream, cli: Cli) -> AnyResult<()> {
     let mut capture = false;
     let mut output_path = ".".to_string();
 
-    match cli.output_format {
+    match output_format {
         OutputFormat::Html => {
-            output_path = cli.output_path.unwrap_or_else(|| "htmlcov/rust".to_string());
+            output_path = cli.output_path.clone().unwrap_or_else(|| "htmlcov/rust".to_string());
             cprint!(out, Green, "Writing HTML coverage to {}", output_path);
             args.extend(["-format=html", "-o", &output_path]);
         }
@@ -154,14 +241,20 @@ fn cov(out: &mut StandardStream, cli: Cli) -> AnyResult<()> {
             cprint!(out, Green, "Generating coverage report");
         }
         OutputFormat::Lcov => {
-            output_path = cli.output_path.unwrap_or_else(|| "rust_coverage.lcov".to_string());
+            output_path = cli.output_path.clone().unwrap_or_else(|| "rust_coverage.lcov".to_string());
             cprint!(out, Green, "Exporting coverage data to {}", output_path);
             args.push("-format=lcov");
             capture = true;
         }
+        OutputFormat::Json => {
+            output_path = cli.output_path.clone().unwrap_or_else(|| "rust_coverage.json".to_string());
+            cprint!(out, Green, "Exporting coverage data to {}", output_path);
+            // `llvm-cov export` produces JSON by default when no `-format` is given
+            capture = true;
+        }
     };
 
-    let output = execute("cov", &args, capture)?;
+    let output = execute("cov", cli.llvm_cov_path.as_deref(), &args, capture)?;
     if let Some(output) = output {
         let mut file = File::create(output_path)?;
         file.write_all(&output)?;
@@ -169,6 +262,131 @@ fn cov(out: &mut StandardStream, cli: Cli) -> AnyResult<()> {
     Ok(())
 }
 
+/// Enforce `--fail-under-*` thresholds, if any were given, by running `llvm-cov export -summary-only`
+/// (whose default output, unlike the `lcov` export, is a JSON summary) and comparing its totals.
+fn check_fail_under(cli: &Cli, demangler: Option<&str>) -> AnyResult<()> {
+    if cli.fail_under_lines.is_none() && cli.fail_under_functions.is_none() && cli.fail_under_regions.is_none() {
+        return Ok(());
+    }
+
+    let profile = format!("-instr-profile={}", PROFDATA_FILE);
+    let mut args = vec!["export", "-summary-only", &profile];
+    args.extend(demangler);
+    let mut ignore_regexes = IGNORE_REGEXES
+        .iter()
+        .map(|r| format!("--ignore-filename-regex={}", r))
+        .collect::<Vec<String>>();
+    cli.ignore_filename_regex
+        .iter()
+        .for_each(|r| ignore_regexes.push(format!("--ignore-filename-regex={}", r)));
+    args.extend(ignore_regexes.iter().map(|f| f.as_str()));
+    args.extend(cli.binaries.iter().map(|f| f.as_str()));
+
+    let output = execute("cov", cli.llvm_cov_path.as_deref(), &args, true)?.unwrap_or_default();
+    let summary: Value = serde_json::from_slice(&output)?;
+    let shortfalls = evaluate_fail_under(
+        &summary,
+        cli.fail_under_lines,
+        cli.fail_under_functions,
+        cli.fail_under_regions,
+    )?;
+    if shortfalls.is_empty() {
+        Ok(())
+    } else {
+        Err(StringError::new(shortfalls.join("\n")).into())
+    }
+}
+
+/// Compare an `llvm-cov export -summary-only` JSON summary's totals against the given thresholds,
+/// returning one message per metric that falls short. Pulled out of `check_fail_under` so the
+/// epsilon/threshold comparisons can be unit tested without shelling out to `llvm-cov`.
+fn evaluate_fail_under(
+    summary: &Value,
+    fail_under_lines: Option<f64>,
+    fail_under_functions: Option<f64>,
+    fail_under_regions: Option<f64>,
+) -> AnyResult<Vec<String>> {
+    let totals = summary["data"]
+        .as_array()
+        .filter(|data| !data.is_empty())
+        .map(|data| &data[0]["totals"])
+        .ok_or_else(|| StringError::new("llvm-cov returned no coverage data, do the binaries match the filters?"))?;
+
+    let mut shortfalls = vec![];
+    for (key, threshold) in [
+        ("lines", fail_under_lines),
+        ("functions", fail_under_functions),
+        ("regions", fail_under_regions),
+    ] {
+        let Some(threshold) = threshold else { continue };
+        let percent = totals[key]["percent"]
+            .as_f64()
+            .ok_or_else(|| StringError::new(format!("llvm-cov summary is missing `{}.percent`", key)))?;
+        if percent + PERCENT_EPSILON < threshold {
+            shortfalls.push(format!(
+                "{} coverage {:.2}% is below the --fail-under-{} threshold of {:.2}%",
+                key, percent, key, threshold
+            ));
+        }
+    }
+    Ok(shortfalls)
+}
+
+/// Recursively walk `dir` collecting the paths of every file for which `matches` returns `true`.
+fn walk_files(dir: &Path, matches: &dyn Fn(&Path) -> AnyResult<bool>, results: &mut Vec<String>) -> AnyResult<()> {
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        if path.is_dir() {
+            walk_files(&path, matches, results)?;
+        } else if path.is_file() && matches(&path)? {
+            results.push(path.to_string_lossy().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Build a `walk_files` predicate matching files with the given extension.
+fn has_extension(ext: &'static str) -> impl Fn(&Path) -> AnyResult<bool> {
+    move |path: &Path| Ok(path.extension() == Some(OsStr::new(ext)))
+}
+
+/// Recursively walk `dir` looking for executable object files, identified by magic bytes rather
+/// than extension, so it works for binaries produced without a `.exe`/`.dll` suffix too.
+fn discover_binaries(dir: &Path) -> AnyResult<Vec<String>> {
+    let mut binaries = vec![];
+    walk_files(dir, &is_executable_object, &mut binaries)?;
+    Ok(binaries)
+}
+
+/// Check the first few bytes of `path` against the magic numbers of ELF, Mach-O and PE/EXE & DLL
+/// binaries, the formats `llvm-cov` can instrument, mirroring grcov's `is_binary` via the `infer` crate.
+fn is_executable_object(path: &Path) -> AnyResult<bool> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 8];
+    let read = file.read(&mut header)?;
+    let header = &header[..read];
+    if header.len() < 4 {
+        return Ok(false);
+    }
+    let is_elf = header.starts_with(&[0x7f, b'E', b'L', b'F']);
+    let is_macho_thin = header.starts_with(&[0xfe, 0xed, 0xfa, 0xce])
+        || header.starts_with(&[0xfe, 0xed, 0xfa, 0xcf])
+        || header.starts_with(&[0xce, 0xfa, 0xed, 0xfe])
+        || header.starts_with(&[0xcf, 0xfa, 0xed, 0xfe]);
+    let is_pe_exe_or_dll = header[0] == b'M' && header[1] == b'Z';
+    // Fat Mach-O binaries and Java `.class` files share the `0xCAFEBABE` magic; disambiguate using
+    // the bytes that follow, as grcov's `infer`-based `is_binary` does.
+    let is_macho_fat = header.starts_with(&[0xca, 0xfe, 0xba, 0xbe]) && header.len() >= 8 && !is_java_class(header);
+    Ok(is_elf || is_macho_thin || is_macho_fat || is_pe_exe_or_dll)
+}
+
+/// A Java `.class` file's `major_version` field (bytes 6-7, big-endian) is always >= 45 (the
+/// format's version as of JDK 1.0.2), whereas a fat Mach-O's `nfat_arch` (bytes 4-7) at that
+/// position is a small architecture count, so `header[6] == 0 && header[7] >= 45` means `.class`.
+fn is_java_class(header: &[u8]) -> bool {
+    header[6] == 0x00 && header[7] >= 45
+}
+
 fn maybe_delete(out: &mut StandardStream, no_delete: bool, profraw_files: Vec<String>) -> AnyResult<()> {
     let mut to_delete = profraw_files;
     to_delete.push(PROFDATA_FILE.to_string());
@@ -183,16 +401,14 @@ fn maybe_delete(out: &mut StandardStream, no_delete: bool, profraw_files: Vec<St
     Ok(())
 }
 
-fn execute(tool_name: &str, args: &[&str], capture: bool) -> Result<Option<Vec<u8>>, StringError> {
-    let path = path(tool_name).map_err(|e| StringError::new(format!("Failed to find tool: {}\n{}", tool_name, e)))?;
-
-    if !path.exists() {
-        return Err(StringError::new(format!(
-            "Could not find tool: {}\nat: {}\nConsider `rustup component add llvm-tools-preview`",
-            tool_name,
-            path.to_string_lossy()
-        )));
-    };
+fn execute(
+    tool_name: &str,
+    override_path: Option<&str>,
+    args: &[&str],
+    capture: bool,
+) -> Result<Option<Vec<u8>>, StringError> {
+    let path = resolve_tool_path(tool_name, override_path)
+        .map_err(|e| StringError::new(format!("Failed to find tool: {}\n{}", tool_name, e)))?;
 
     let cmd_display = format!("{} {}", tool_name, args.join(" "));
 
@@ -253,10 +469,49 @@ impl Error for StringError {
     }
 }
 
-fn path(tool_name: &str) -> AnyResult<PathBuf> {
-    let mut path = rustlib()?;
-    path.push(format!("llvm-{}{}", tool_name, EXE_SUFFIX));
-    Ok(path)
+/// Resolve `llvm-<tool_name>`, in order: an explicit `--llvm-*-path` override, the rustlib bin dir
+/// (the traditional home of `llvm-tools-preview`), then `$PATH`. The rustlib bin dir is cached
+/// behind a `OnceCell` since computing it shells out to `rustc --print sysroot`.
+fn resolve_tool_path(tool_name: &str, override_path: Option<&str>) -> AnyResult<PathBuf> {
+    if let Some(override_path) = override_path {
+        return Ok(PathBuf::from(override_path));
+    }
+
+    let exe_name = format!("llvm-{}{}", tool_name, EXE_SUFFIX);
+
+    if let Some(dir) = rustlib_bin_dir() {
+        let candidate = dir.join(&exe_name);
+        if candidate.exists() {
+            return Ok(candidate);
+        }
+    }
+
+    if let Some(candidate) = find_on_path(&exe_name) {
+        return Ok(candidate);
+    }
+
+    Err(StringError::new(format!(
+        "Could not find {} in the rustlib bin dir or $PATH\nConsider `rustup component add llvm-tools-preview`",
+        exe_name
+    ))
+    .into())
+}
+
+/// The rustlib bin dir, computed at most once per run; `None` if it couldn't be determined.
+fn rustlib_bin_dir() -> Option<&'static PathBuf> {
+    static RUSTLIB_BIN_DIR: OnceCell<Option<PathBuf>> = OnceCell::new();
+    RUSTLIB_BIN_DIR.get_or_init(|| rustlib().ok()).as_ref()
+}
+
+fn find_on_path(exe_name: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths).map(|dir| dir.join(exe_name)).find(|c| c.is_file())
+}
+
+/// Whether `rustfilt` is available on `$PATH`, checked at most once per run.
+fn rustfilt_available() -> bool {
+    static RUSTFILT_AVAILABLE: OnceCell<bool> = OnceCell::new();
+    *RUSTFILT_AVAILABLE.get_or_init(|| find_on_path(&format!("rustfilt{}", EXE_SUFFIX)).is_some())
 }
 
 // see https://github.com/rust-embedded/cargo-binutils/blob/36102732f7535b4730f7cd66c670ebe3959994ef/src/rustc.rs#L7-L23
@@ -276,3 +531,125 @@ pub fn rustlib() -> AnyResult<PathBuf> {
     pathbuf.push("bin");
     Ok(pathbuf)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn temp_file(bytes: &[u8]) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("coverage-prepare-test-{}-{}", process::id(), id));
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn is_executable_object_detects_elf() {
+        let path = temp_file(&[0x7f, b'E', b'L', b'F', 0x02, 0x01, 0x01, 0x00]);
+        assert!(is_executable_object(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn is_executable_object_detects_macho_thin() {
+        let path = temp_file(&[0xfe, 0xed, 0xfa, 0xce, 0x00, 0x00, 0x00, 0x00]);
+        assert!(is_executable_object(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn is_executable_object_detects_macho_fat() {
+        // nfat_arch = 2
+        let path = temp_file(&[0xca, 0xfe, 0xba, 0xbe, 0x00, 0x00, 0x00, 0x02]);
+        assert!(is_executable_object(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn is_executable_object_detects_pe() {
+        let path = temp_file(&[b'M', b'Z', 0x90, 0x00]);
+        assert!(is_executable_object(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn is_executable_object_rejects_java_class_file() {
+        // minor_version = 0, major_version = 52 (Java 8), shares CAFEBABE with fat Mach-O
+        let path = temp_file(&[0xca, 0xfe, 0xba, 0xbe, 0x00, 0x00, 0x00, 0x34]);
+        assert!(!is_executable_object(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn is_executable_object_rejects_plain_text() {
+        let path = temp_file(b"just some text");
+        assert!(!is_executable_object(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn walk_files_recurses_into_subdirectories() {
+        let root = env::temp_dir().join(format!("coverage-prepare-walk-test-{}", process::id()));
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("a.profraw"), b"").unwrap();
+        fs::write(root.join("nested").join("b.profraw"), b"").unwrap();
+        fs::write(root.join("c.txt"), b"").unwrap();
+
+        let mut found = vec![];
+        walk_files(&root, &has_extension("profraw"), &mut found).unwrap();
+        found.sort();
+
+        let mut expected = vec![
+            root.join("a.profraw").to_string_lossy().to_string(),
+            root.join("nested").join("b.profraw").to_string_lossy().to_string(),
+        ];
+        expected.sort();
+
+        assert_eq!(found, expected);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    fn summary_with_totals(lines: f64, functions: f64, regions: f64) -> Value {
+        serde_json::json!({
+            "data": [{
+                "totals": {
+                    "lines": {"percent": lines},
+                    "functions": {"percent": functions},
+                    "regions": {"percent": regions},
+                }
+            }]
+        })
+    }
+
+    #[test]
+    fn evaluate_fail_under_passes_within_epsilon_of_threshold() {
+        let summary = summary_with_totals(80.0 - PERCENT_EPSILON / 2.0, 100.0, 100.0);
+        let shortfalls = evaluate_fail_under(&summary, Some(80.0), None, None).unwrap();
+        assert!(shortfalls.is_empty());
+    }
+
+    #[test]
+    fn evaluate_fail_under_flags_genuine_shortfalls() {
+        let summary = summary_with_totals(79.0, 50.0, 100.0);
+        let shortfalls = evaluate_fail_under(&summary, Some(80.0), Some(60.0), Some(90.0)).unwrap();
+        assert_eq!(shortfalls.len(), 2);
+        assert!(shortfalls[0].contains("lines"));
+        assert!(shortfalls[1].contains("functions"));
+    }
+
+    #[test]
+    fn evaluate_fail_under_ignores_unset_thresholds() {
+        let summary = summary_with_totals(0.0, 0.0, 0.0);
+        let shortfalls = evaluate_fail_under(&summary, None, None, None).unwrap();
+        assert!(shortfalls.is_empty());
+    }
+
+    #[test]
+    fn evaluate_fail_under_errors_on_empty_data() {
+        let summary = serde_json::json!({"data": []});
+        assert!(evaluate_fail_under(&summary, Some(80.0), None, None).is_err());
+    }
+}